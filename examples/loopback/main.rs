@@ -1,16 +1,15 @@
-use bevy_microphone::{AudioManager, AudioSettings, SampleRate};
-use rodio::buffer::SamplesBuffer;
-use rodio::{OutputStream, OutputStreamBuilder, Sink};
+use bevy_microphone::{AudioManager, AudioSettings, FrameSize};
+use std::thread;
+use std::time::Duration;
 pub fn main() {
     let mut audio = AudioManager::new(&AudioSettings::default());
-    let stream_handle = OutputStreamBuilder::open_default_stream().unwrap();
-    let sink = Sink::connect_new(stream_handle.mixer());
+    let time = FrameSize::default().time() as u64;
     loop {
-        audio.recv_audio_decode(|data| {
-            let source =
-                SamplesBuffer::new(1, (SampleRate::default().get_number() * 1000) as u32, data);
-            sink.append(source);
-            sink.play()
-        });
+        let mut frames = Vec::new();
+        audio.try_recv_audio_decode(|data| frames.push(data.to_vec()));
+        for frame in &frames {
+            audio.push_playback(frame);
+        }
+        thread::sleep(Duration::from_micros(time));
     }
 }
@@ -3,9 +3,10 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{BufferSize, StreamConfig};
 use opus::{Application, Channels, Decoder, Encoder};
 use rubato::{Fft, FixedSync, Resampler};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
-use std::sync::{Arc, mpsc};
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 use std::time::Duration;
 #[cfg(feature = "log")]
@@ -54,25 +55,390 @@ impl AudioResource {
     pub fn stop(&self, b: bool) {
         self.lock().stop(b)
     }
+    pub fn push_playback(&self, frame: &[f32]) {
+        self.lock().push_playback(frame)
+    }
+    pub fn set_volume(&self, volume: f32) {
+        self.lock().set_volume(volume)
+    }
+    pub fn volume(&self) -> f32 {
+        self.lock().volume()
+    }
 }
 pub struct AudioManager {
     rx: Receiver<Vec<u8>>,
-    decoder: Decoder,
+    decoder: FrameDecoder,
     kill: Arc<AtomicBool>,
     stop: Arc<AtomicBool>,
+    playback: Arc<Mutex<PcmBuffers>>,
+    /// Decode-rate frames pushed before the output thread has decided whether resampling is
+    /// needed; drained into `playback` (resampled if required) as soon as it decides.
+    playback_pending: Arc<Mutex<Vec<f32>>>,
+    playback_resampler: Arc<Mutex<PlaybackResamplerState>>,
+    volume: Arc<Mutex<f32>>,
 }
 impl Drop for AudioManager {
     fn drop(&mut self) {
         self.kill();
     }
 }
+/// A queue of decoded PCM frames that an output stream callback drains sample-by-sample.
+///
+/// Frames are pushed whole with [`PcmBuffers::produce`] and drained with
+/// [`PcmBuffers::consume_exact`], which walks across frame boundaries so the output
+/// callback never needs to know the producer's frame size.
+#[derive(Default)]
+pub struct PcmBuffers {
+    buffers: Vec<Vec<f32>>,
+    consumer_cursor: usize,
+}
+impl PcmBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn produce(&mut self, frame: Vec<f32>) {
+        if !frame.is_empty() {
+            self.buffers.push(frame);
+        }
+    }
+    pub fn samples_available(&self) -> usize {
+        self.buffers
+            .first()
+            .map(|buf| buf.len() - self.consumer_cursor)
+            .unwrap_or(0)
+            + self.buffers.iter().skip(1).map(|buf| buf.len()).sum::<usize>()
+    }
+    /// Fills `out` from the queued frames, returning `false` (without writing to `out` or
+    /// consuming any queued sample) when fewer than `out.len()` samples are available.
+    pub fn consume_exact(&mut self, out: &mut [f32]) -> bool {
+        if self.samples_available() < out.len() {
+            return false;
+        }
+        let mut filled = 0;
+        while filled < out.len() {
+            let buf = &self.buffers[0];
+            let available = buf.len() - self.consumer_cursor;
+            let take = available.min(out.len() - filled);
+            out[filled..filled + take]
+                .copy_from_slice(&buf[self.consumer_cursor..self.consumer_cursor + take]);
+            self.consumer_cursor += take;
+            filled += take;
+            if self.consumer_cursor >= buf.len() {
+                self.buffers.remove(0);
+                self.consumer_cursor = 0;
+            }
+        }
+        true
+    }
+}
+/// Consecutive silent frames a source may produce before [`AudioMixer`] drops it.
+const MIXER_SOURCE_TIMEOUT: usize = 50;
+struct MixerSource {
+    decoder: FrameDecoder,
+    buffer: PcmBuffers,
+    empty_frames: usize,
+}
+/// Mixes Opus packets from several peers into a single PCM stream.
+///
+/// Each source gets its own [`FrameDecoder`] (Opus decoding is stateful per-stream, and each
+/// peer's packets are sequenced independently) and its own [`PcmBuffers`] ring so sources that
+/// arrive at slightly different paces don't desync. [`AudioMixer::mix`] sums every active
+/// source's next frame, clamped to `[-1.0, 1.0]`, and drops sources that have been silent for
+/// [`MIXER_SOURCE_TIMEOUT`] frames in a row. The mixed frame can be handed straight to
+/// [`AudioManager::push_playback`].
+pub struct AudioMixer {
+    sources: HashMap<u64, MixerSource>,
+    sample_rate: SampleRate,
+    channels: Channels,
+    max_reorder_depth: usize,
+    loss_deadline: Duration,
+}
+impl AudioMixer {
+    pub fn new(settings: &AudioSettings) -> Self {
+        Self {
+            sources: HashMap::new(),
+            sample_rate: settings.sample_rate,
+            channels: settings.channels,
+            max_reorder_depth: settings.max_reorder_depth,
+            loss_deadline: settings.loss_deadline,
+        }
+    }
+    /// Decodes a framed Opus packet from `source_id` and queues the PCM into that source's
+    /// buffer.
+    pub fn push(&mut self, source_id: u64, data: &[u8]) {
+        let sample_rate = self.sample_rate;
+        let channels = self.channels;
+        let max_reorder_depth = self.max_reorder_depth;
+        let loss_deadline = self.loss_deadline;
+        let source = self.sources.entry(source_id).or_insert_with(|| MixerSource {
+            decoder: FrameDecoder::new(sample_rate, channels, max_reorder_depth, loss_deadline),
+            buffer: PcmBuffers::new(),
+            empty_frames: 0,
+        });
+        let mut frames = Vec::new();
+        source.decoder.push(data, |frame| frames.push(frame.to_vec()));
+        for frame in frames {
+            source.buffer.produce(frame);
+        }
+    }
+    /// Sums one frame from every active source into `out`, clamping to `[-1.0, 1.0]`.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        let mut scratch = vec![0.0; out.len()];
+        self.sources.retain(|_, source| {
+            if source.buffer.consume_exact(&mut scratch) {
+                source.empty_frames = 0;
+                for (o, s) in out.iter_mut().zip(scratch.iter()) {
+                    *o += *s;
+                }
+            } else {
+                source.empty_frames += 1;
+            }
+            source.empty_frames < MIXER_SOURCE_TIMEOUT
+        });
+        for o in out.iter_mut() {
+            *o = o.clamp(-1.0, 1.0);
+        }
+    }
+}
+#[cfg(feature = "bevy")]
+#[derive(bevy_ecs::prelude::Resource)]
+pub struct AudioMixerResource(std::sync::Mutex<AudioMixer>);
+#[cfg(feature = "bevy")]
+impl AudioMixerResource {
+    pub fn new(settings: &AudioSettings) -> Self {
+        Self(AudioMixer::new(settings).into())
+    }
+    pub fn push(&self, source_id: u64, data: &[u8]) {
+        self.0.lock().unwrap().push(source_id, data)
+    }
+    pub fn mix(&self, out: &mut [f32]) {
+        self.0.lock().unwrap().mix(out)
+    }
+}
+/// Bytes of wrapping `u32` sequence number prepended to every encoded Opus packet.
+const FRAME_HEADER_LEN: usize = 4;
+fn encode_frame(seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    framed.extend_from_slice(&seq.to_le_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+fn split_frame(data: &[u8]) -> Option<(u32, &[u8])> {
+    let header = data.get(..FRAME_HEADER_LEN)?;
+    Some((
+        u32::from_le_bytes(header.try_into().unwrap()),
+        &data[FRAME_HEADER_LEN..],
+    ))
+}
+/// Reorders framed Opus packets by the sequence number [`encode_frame`] writes, and conceals
+/// gaps with Opus packet-loss concealment once `loss_deadline` expires or too many packets
+/// have piled up waiting on the missing one. If the packet after the gap arrives, its in-band
+/// FEC data is decoded first to recover the lost frame, then its own audio is decoded normally.
+struct FrameDecoder {
+    decoder: Decoder,
+    expected: Option<u32>,
+    pending: std::collections::BTreeMap<u32, Vec<u8>>,
+    max_reorder_depth: usize,
+    loss_deadline: Duration,
+    gap_since: Option<std::time::Instant>,
+}
+impl FrameDecoder {
+    fn new(
+        sample_rate: SampleRate,
+        channels: Channels,
+        max_reorder_depth: usize,
+        loss_deadline: Duration,
+    ) -> Self {
+        Self {
+            decoder: Decoder::new((sample_rate.get_number() * 1000) as u32, channels).unwrap(),
+            expected: None,
+            pending: Default::default(),
+            max_reorder_depth,
+            loss_deadline,
+            gap_since: None,
+        }
+    }
+    /// Feeds one framed packet from the channel and decodes zero or more PCM frames into `f`.
+    ///
+    /// `expected` always advances by exactly one sequence number per slot it accounts for, so
+    /// every real encoded frame produces exactly one decoded frame here: never two (blind PLC
+    /// followed by a redundant FEC recovery of the same slot) and never zero (a loss that never
+    /// resolves stalling the decoder forever).
+    fn push<F: FnMut(&mut [f32])>(&mut self, data: &[u8], mut f: F) {
+        let Some((seq, payload)) = split_frame(data) else {
+            return;
+        };
+        self.pending.insert(seq, payload.to_vec());
+        if self.expected.is_none() {
+            self.expected = Some(seq);
+        }
+        let out = &mut [0.0; 2048];
+        loop {
+            let expected = self.expected.unwrap();
+            if let Some(payload) = self.pending.remove(&expected) {
+                if let Ok(len) = self.decoder.decode_float(&payload, out, false)
+                    && len != 0
+                {
+                    f(&mut out[..len])
+                }
+                self.expected = Some(expected.wrapping_add(1));
+                self.gap_since = None;
+                continue;
+            }
+            if self.pending.is_empty() {
+                break;
+            }
+            let gap_since = *self.gap_since.get_or_insert_with(std::time::Instant::now);
+            let within_budget = gap_since.elapsed() < self.loss_deadline
+                && self.pending.len() <= self.max_reorder_depth;
+            if within_budget {
+                break;
+            }
+            // `expected` has been missing too long, or too many later packets have piled up
+            // waiting on it: give up on it. Recover it from the following packet's in-band FEC
+            // data if that packet has already arrived, otherwise fall back to blind
+            // concealment. Either way `expected` advances here, so this bailout stays
+            // reachable on every iteration — a run of several consecutive losses each get
+            // forced forward in turn instead of latching on the first one. libopus only
+            // conceals or recovers a multiple of 2.5 ms, so the lost slot is sized to the
+            // last packet's duration rather than the whole scratch buffer.
+            let lost = self
+                .decoder
+                .get_last_packet_duration()
+                .map_or(0, |d| d as usize)
+                .min(out.len());
+            if let Some(next_payload) = self.pending.remove(&expected.wrapping_add(1)) {
+                if let Ok(len) = self
+                    .decoder
+                    .decode_float(&next_payload, &mut out[..lost], true)
+                    && len != 0
+                {
+                    f(&mut out[..len])
+                }
+                if let Ok(len) = self.decoder.decode_float(&next_payload, out, false)
+                    && len != 0
+                {
+                    f(&mut out[..len])
+                }
+                self.expected = Some(expected.wrapping_add(2));
+            } else {
+                if let Ok(len) = self.decoder.decode_float(&[], &mut out[..lost], false)
+                    && len != 0
+                {
+                    f(&mut out[..len])
+                }
+                self.expected = Some(expected.wrapping_add(1));
+            }
+            self.gap_since = None;
+        }
+    }
+}
+#[cfg(test)]
+mod frame_decoder_tests {
+    use super::*;
+
+    #[test]
+    fn loss_is_concealed_without_duplicating_or_stalling_frames() {
+        let mut encoder = Encoder::new(48000, Channels::Mono, Application::Audio).unwrap();
+        let mut decoder = FrameDecoder::new(SampleRate::SR48, Channels::Mono, 8, Duration::ZERO);
+        let silence = [0f32; 480];
+        let mut compressed = [0u8; 2048];
+        let mut frames = 0;
+        // seq 1 and 2 are dropped; the decoder should still land on exactly one decoded
+        // frame per sequence slot (0..=4): no duplicate from conceal-then-FEC-recover of
+        // the same slot, and no permanent stall once 3 and 4 arrive.
+        for seq in [0u32, 3, 4] {
+            let len = encoder.encode_float(&silence, &mut compressed).unwrap();
+            decoder.push(&encode_frame(seq, &compressed[..len]), |_| frames += 1);
+        }
+        assert_eq!(frames, 5);
+    }
+}
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+/// Resamples decoded PCM from the Opus decode rate to the output device's actual rate.
+///
+/// The repeating block size is derived from the `gcd` of the two rates (the smallest chunk
+/// that converts between them with no fractional remainder) rather than the encode/decode
+/// `FrameSize`, since the device rate is unrelated to the Opus frame size in use.
+struct PlaybackResampler {
+    resampler: Fft<f32>,
+    in_block: usize,
+    out_block: usize,
+    extra: Vec<f32>,
+}
+impl PlaybackResampler {
+    /// Returns `Ok(None)` when the rates already match and no resampling is needed. Returns
+    /// `Err` if `rubato` failed to construct a resampler for these rates, distinct from the
+    /// rates-match case so a construction failure can be logged and handled instead of being
+    /// silently treated as "no resampling needed" and mis-pitching playback.
+    fn new(
+        decode_rate: usize,
+        device_rate: usize,
+    ) -> Result<Option<Self>, rubato::ResamplerConstructionError> {
+        if decode_rate == device_rate {
+            return Ok(None);
+        }
+        let divisor = gcd(decode_rate, device_rate);
+        let in_block = decode_rate / divisor;
+        let out_block = device_rate / divisor;
+        let resampler = Fft::<f32>::new(decode_rate, device_rate, in_block, 8, 1, FixedSync::Both)?;
+        Ok(Some(Self {
+            resampler,
+            in_block,
+            out_block,
+            extra: Vec::with_capacity(in_block),
+        }))
+    }
+    /// Resamples `frame`, appending one device-rate block to `out` for every complete
+    /// `in_block` of decoded PCM that accumulates.
+    fn process(&mut self, frame: &[f32], out: &mut Vec<Vec<f32>>) {
+        self.extra.extend_from_slice(frame);
+        while self.extra.len() >= self.in_block {
+            let input = InterleavedSlice::new(&self.extra[..self.in_block], 1, self.in_block)
+                .unwrap();
+            let mut buffer = vec![0f32; self.out_block];
+            let mut output = InterleavedSlice::new_mut(&mut buffer, 1, self.out_block).unwrap();
+            self.resampler
+                .process_into_buffer(&input, &mut output, None)
+                .unwrap();
+            out.push(buffer);
+            self.extra.drain(..self.in_block);
+        }
+    }
+}
+/// Upper bound, in samples, on [`AudioManager::playback_pending`]. If an output device is never
+/// found, or its config never opens, `playback_resampler` stays `Pending` forever and every
+/// [`AudioManager::push_playback`] call would otherwise keep growing that backlog without limit;
+/// once it's full the oldest samples are dropped to make room for new ones.
+const PLAYBACK_PENDING_CAP: usize = 48_000 * 2;
+/// What [`AudioManager::push_playback`] should do with a decode-rate frame.
+///
+/// The output thread doesn't know whether the device needs resampling until it has opened it,
+/// which happens asynchronously on its own thread. Frames pushed before that decision lands stay
+/// `Pending` in [`AudioManager::playback_pending`] instead of being guessed into the device-rate
+/// buffer untouched, which would glitch the first burst of playback whenever the rates differ.
+enum PlaybackResamplerState {
+    Pending,
+    Passthrough,
+    Resample(PlaybackResampler),
+}
 #[cfg_attr(feature = "bevy", derive(bevy_ecs::prelude::Resource))]
 pub struct AudioSettings {
     pub input_device: Option<String>,
+    pub output_device: Option<String>,
     pub channels: Channels, //TODO only mono implemented
     pub frame_size: FrameSize,
     pub sample_rate: SampleRate,
     pub application: Application,
+    /// How many packets a [`FrameDecoder`] may hold out of sequence order before it gives up
+    /// waiting and conceals the gap.
+    pub max_reorder_depth: usize,
+    /// How long a [`FrameDecoder`] waits for an out-of-order packet before conceding it lost
+    /// and invoking Opus packet-loss concealment.
+    pub loss_deadline: Duration,
 }
 #[derive(Clone, Copy, Default)]
 pub enum SampleRate {
@@ -129,10 +495,13 @@ impl Default for AudioSettings {
     fn default() -> Self {
         Self {
             input_device: None,
+            output_device: None,
             channels: Channels::Mono,
             frame_size: FrameSize::default(),
             sample_rate: SampleRate::default(),
             application: Application::Audio,
+            max_reorder_depth: 8,
+            loss_deadline: Duration::from_millis(60),
         }
     }
 }
@@ -140,11 +509,9 @@ impl AudioManager {
     pub fn kill(&self) {
         self.kill.store(true, Ordering::Relaxed);
     }
-    pub fn new(settings: &AudioSettings) -> Self {
-        let channels = settings.channels;
-        let frame_size = settings.frame_size;
-        let sample_rate = settings.sample_rate;
-        let application = settings.application;
+    /// Opens the host this crate prefers: JACK on Linux when available, the default host
+    /// otherwise.
+    fn host() -> cpal::Host {
         #[cfg(target_os = "linux")]
         let host = cpal::available_hosts()
             .into_iter()
@@ -153,6 +520,60 @@ impl AudioManager {
             .unwrap_or(cpal::default_host());
         #[cfg(not(target_os = "linux"))]
         let host = cpal::default_host();
+        host
+    }
+    /// Lists input device descriptions, suffixing the host's default device with `(default)`.
+    pub fn input_devices() -> Vec<String> {
+        let host = Self::host();
+        let default_name = host
+            .default_input_device()
+            .and_then(|d| d.description().ok())
+            .map(|d| d.name().to_string());
+        host.input_devices()
+            .map(|devices| {
+                devices
+                    .filter_map(|d| d.description().ok())
+                    .map(|desc| {
+                        let name = desc.name().to_string();
+                        if default_name.as_deref() == Some(name.as_str()) {
+                            format!("{name} (default)")
+                        } else {
+                            name
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// Lists output device descriptions, suffixing the host's default device with `(default)`.
+    pub fn output_devices() -> Vec<String> {
+        let host = Self::host();
+        let default_name = host
+            .default_output_device()
+            .and_then(|d| d.description().ok())
+            .map(|d| d.name().to_string());
+        host.output_devices()
+            .map(|devices| {
+                devices
+                    .filter_map(|d| d.description().ok())
+                    .map(|desc| {
+                        let name = desc.name().to_string();
+                        if default_name.as_deref() == Some(name.as_str()) {
+                            format!("{name} (default)")
+                        } else {
+                            name
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    pub fn new(settings: &AudioSettings) -> Self {
+        let channels = settings.channels;
+        let frame_size = settings.frame_size;
+        let sample_rate = settings.sample_rate;
+        let application = settings.application;
+        let host = Self::host();
         let device = {
             if let Some(input) = &settings.input_device
                 && let Some(d) = host
@@ -173,7 +594,12 @@ impl AudioManager {
                 host.default_input_device()
             }
         };
-        let decoder = Decoder::new((sample_rate.get_number() * 1000) as u32, channels).unwrap();
+        let decoder = FrameDecoder::new(
+            sample_rate,
+            channels,
+            settings.max_reorder_depth,
+            settings.loss_deadline,
+        );
         let (tx, rx) = mpsc::channel::<Vec<u8>>();
         let kill: Arc<AtomicBool> = AtomicBool::new(false).into();
         let kill2 = kill.clone();
@@ -218,6 +644,7 @@ impl AudioManager {
                     let mut extra = Vec::with_capacity(input_frame_size);
                     let mut compressed = [0u8; 2048];
                     let mut buffer = [0f32; 2880];
+                    let mut seq = 0u32;
                     match device.build_input_stream(
                         &config,
                         move |data: &[f32], _| {
@@ -250,7 +677,8 @@ impl AudioManager {
                                 if let Ok(len) = encoder.encode_float(buf, &mut compressed)
                                     && len != 0
                                 {
-                                    let _ = tx.send(compressed[..len].to_vec());
+                                    let _ = tx.send(encode_frame(seq, &compressed[..len]));
+                                    seq = seq.wrapping_add(1);
                                 }
                                 extra.drain(..input_frame_size);
                             }
@@ -294,13 +722,183 @@ impl AudioManager {
                 warn!("input device not found")
             }
         });
+        let output_device = settings.output_device.clone();
+        let playback: Arc<Mutex<PcmBuffers>> = Mutex::new(PcmBuffers::new()).into();
+        let playback2 = playback.clone();
+        let playback3 = playback.clone();
+        let playback_resampler: Arc<Mutex<PlaybackResamplerState>> =
+            Mutex::new(PlaybackResamplerState::Pending).into();
+        let playback_resampler2 = playback_resampler.clone();
+        let playback_pending: Arc<Mutex<Vec<f32>>> = Mutex::new(Vec::new()).into();
+        let playback_pending2 = playback_pending.clone();
+        let volume: Arc<Mutex<f32>> = Mutex::new(1.0).into();
+        let kill3 = kill.clone();
+        thread::spawn(move || {
+            let device = if let Some(output) = &output_device
+                && let Some(d) = host
+                    .output_devices()
+                    .map(|mut d| {
+                        d.find(|d| {
+                            d.description()
+                                .ok()
+                                .map(|a| output == a.name())
+                                .unwrap_or(false)
+                        })
+                    })
+                    .ok()
+                    .flatten()
+            {
+                Some(d)
+            } else {
+                host.default_output_device()
+            };
+            if let Some(device) = device {
+                if let Ok(cfg) = device.default_output_config() {
+                    let sample = cfg.sample_rate();
+                    let resampler = match PlaybackResampler::new(
+                        sample_rate.get_number() * 1000,
+                        sample as usize,
+                    ) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            error!("{e}");
+                            return;
+                        }
+                    };
+                    // Lock order is always `playback_resampler` before `playback`, matching
+                    // `push_playback`, so the pending backlog is drained exactly once and no
+                    // frame pushed after this point can be skipped or duplicated.
+                    {
+                        let mut state = playback_resampler2.lock().unwrap();
+                        let mut playback = playback3.lock().unwrap();
+                        let pending = std::mem::take(&mut *playback_pending2.lock().unwrap());
+                        *state = match resampler {
+                            Some(mut resampler) => {
+                                let mut resampled = Vec::new();
+                                resampler.process(&pending, &mut resampled);
+                                for frame in resampled {
+                                    playback.produce(frame);
+                                }
+                                PlaybackResamplerState::Resample(resampler)
+                            }
+                            None => {
+                                playback.produce(pending);
+                                PlaybackResamplerState::Passthrough
+                            }
+                        };
+                    }
+                    let time = frame_size.time() as u64;
+                    let config = StreamConfig {
+                        channels: 1,
+                        sample_rate: sample,
+                        buffer_size: BufferSize::Default,
+                    };
+                    match device.build_output_stream(
+                        &config,
+                        move |data: &mut [f32], _| {
+                            if !playback2.lock().unwrap().consume_exact(data) {
+                                data.fill(0.0);
+                            }
+                        },
+                        |_err| {
+                            #[cfg(feature = "log")]
+                            error!("Stream error: {}", _err)
+                        },
+                        None,
+                    ) {
+                        Ok(stream) => {
+                            if let Ok(_s) = stream.play() {
+                                loop {
+                                    if kill3.load(Ordering::Relaxed) {
+                                        return;
+                                    }
+                                    thread::sleep(Duration::from_micros(time))
+                                }
+                            } else {
+                                #[cfg(feature = "log")]
+                                error!("failed to play output stream")
+                            }
+                        }
+                        Err(_s) => {
+                            #[cfg(feature = "log")]
+                            error!(
+                                "no output stream {}, {}, {}, {}",
+                                _s,
+                                cfg.channels(),
+                                cfg.sample_rate(),
+                                cfg.sample_format()
+                            )
+                        }
+                    }
+                } else {
+                    #[cfg(feature = "log")]
+                    warn!("output config not found")
+                }
+            } else {
+                #[cfg(feature = "log")]
+                warn!("output device not found")
+            }
+        });
         Self {
             rx,
             decoder,
             kill,
             stop,
+            playback,
+            playback_pending,
+            playback_resampler,
+            volume,
+        }
+    }
+    /// Queues a decoded PCM frame for playback, applying [`AudioManager::set_volume`] and
+    /// resampling to the output device's rate before it reaches the output stream's callback.
+    ///
+    /// Until the output thread has opened the device and decided whether resampling is needed,
+    /// frames accumulate in `playback_pending` instead of being guessed into the device-rate
+    /// buffer untouched; the output thread drains and (if needed) resamples that backlog as soon
+    /// as it makes its decision.
+    pub fn push_playback(&self, frame: &[f32]) {
+        let volume = *self.volume.lock().unwrap();
+        let scaled: Vec<f32> = if volume == 1.0 {
+            frame.to_vec()
+        } else {
+            frame.iter().map(|s| s * volume).collect()
+        };
+        // Lock order is always `playback_resampler` before `playback`, matching the output
+        // thread's drain, so no frame can be appended to `playback_pending` after it's been
+        // drained (which would otherwise strand it there forever).
+        let mut state = self.playback_resampler.lock().unwrap();
+        match &mut *state {
+            PlaybackResamplerState::Pending => {
+                let mut pending = self.playback_pending.lock().unwrap();
+                pending.extend(scaled);
+                if pending.len() > PLAYBACK_PENDING_CAP {
+                    let overflow = pending.len() - PLAYBACK_PENDING_CAP;
+                    pending.drain(..overflow);
+                    #[cfg(feature = "log")]
+                    warn!("playback_pending exceeded cap; dropping oldest samples");
+                }
+            }
+            PlaybackResamplerState::Passthrough => {
+                self.playback.lock().unwrap().produce(scaled);
+            }
+            PlaybackResamplerState::Resample(resampler) => {
+                let mut resampled = Vec::new();
+                resampler.process(&scaled, &mut resampled);
+                let mut playback = self.playback.lock().unwrap();
+                for frame in resampled {
+                    playback.produce(frame);
+                }
+            }
         }
     }
+    /// Sets the gain applied to frames pushed with [`AudioManager::push_playback`].
+    pub fn set_volume(&self, volume: f32) {
+        *self.volume.lock().unwrap() = volume;
+    }
+    pub fn volume(&self) -> f32 {
+        *self.volume.lock().unwrap()
+    }
     pub fn try_recv_audio<F>(&self, mut f: F)
     where
         F: FnMut(Vec<u8>),
@@ -324,37 +922,22 @@ impl AudioManager {
     where
         F: FnMut(&mut [f32]),
     {
-        let out = &mut [0.0; 2048];
         while let Ok(data) = self.rx.try_recv() {
-            if let Ok(len) = self.decoder.decode_float(&data, out, false)
-                && len != 0
-            {
-                f(&mut out[..len])
-            }
+            self.decoder.push(&data, &mut f);
         }
     }
     pub fn recv_audio_decode<F>(&mut self, mut f: F)
     where
         F: FnMut(&mut [f32]),
     {
-        let out = &mut [0.0; 2048];
         while let Ok(data) = self.rx.recv() {
-            if let Ok(len) = self.decoder.decode_float(&data, out, false)
-                && len != 0
-            {
-                f(&mut out[..len])
-            }
+            self.decoder.push(&data, &mut f);
         }
     }
     pub fn decode<F>(&mut self, data: Vec<u8>, mut f: F)
     where
         F: FnMut(&mut [f32]),
     {
-        let out = &mut [0.0; 2048];
-        if let Ok(len) = self.decoder.decode_float(&data, out, false)
-            && len != 0
-        {
-            f(&mut out[..len])
-        }
+        self.decoder.push(&data, &mut f);
     }
 }